@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::fs;
+
+const CONFIG_PATH: &str = "config.toml";
+
+// A flat key/value settings store backed by a TOML file next to
+// `history.txt`. Keys used by the rest of the REPL: `default_protocol`
+// (`swd`/`jtag`), `trace_clock` (Hz), `default_dump_addr` (hex address
+// bookmark), `alias.<name>` (hex address aliases usable anywhere a memory
+// address is expected, e.g. `config write alias.flash_base 0x08000000`).
+pub struct Config {
+    values: HashMap<String, String>,
+}
+
+pub enum ConfigAction {
+    Read(String),
+    Write(String, String),
+    Remove(String),
+}
+
+pub fn parse_config_command(rest: &[&str]) -> Result<ConfigAction, String> {
+    match rest.split_first() {
+        Some((&"read", args)) if args.len() == 1 => Ok(ConfigAction::Read(args[0].to_string())),
+        Some((&"write", args)) if args.len() == 2 => Ok(ConfigAction::Write(
+            args[0].to_string(),
+            args[1].to_string(),
+        )),
+        Some((&"remove", args)) if args.len() == 1 => Ok(ConfigAction::Remove(args[0].to_string())),
+        _ => Err(
+            "Usage: config read <key> | config write <key> <value> | config remove <key>"
+                .to_string(),
+        ),
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let values = fs::read_to_string(CONFIG_PATH)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default();
+        Config { values }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), &'static str> {
+        self.values.insert(key.to_string(), value.to_string());
+        self.save()
+    }
+
+    pub fn remove(&mut self, key: &str) -> Result<(), &'static str> {
+        self.values.remove(key);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), &'static str> {
+        let contents = toml::to_string(&self.values).or(Err("Failed to serialize config"))?;
+        fs::write(CONFIG_PATH, contents).or(Err("Failed to write config file"))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_map(values: HashMap<String, String>) -> Self {
+        Config { values }
+    }
+}
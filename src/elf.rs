@@ -0,0 +1,406 @@
+// Minimal 32-bit ELF parsing, just enough to pull the loadable segments out
+// of a firmware image for flashing and the `.defmt` format string table out
+// of it for decoding RTT logs.
+
+use std::collections::HashMap;
+
+pub struct Segment {
+    pub addr: u32,
+    pub data: Vec<u8>,
+}
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+
+fn check_header(bytes: &[u8]) -> Result<bool, &'static str> {
+    if bytes.len() < 52 || &bytes[0..4] != b"\x7fELF" {
+        return Err("Not an ELF file");
+    }
+    if bytes[4] != 1 {
+        return Err("Only 32-bit ELF files are supported");
+    }
+    Ok(bytes[5] == 1)
+}
+
+fn read_u16(bytes: &[u8], off: usize, le: bool) -> u16 {
+    let b = &bytes[off..off + 2];
+    if le {
+        u16::from_le_bytes([b[0], b[1]])
+    } else {
+        u16::from_be_bytes([b[0], b[1]])
+    }
+}
+
+fn read_u32(bytes: &[u8], off: usize, le: bool) -> u32 {
+    let b = &bytes[off..off + 4];
+    if le {
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    } else {
+        u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+    }
+}
+
+fn read_cstr(bytes: &[u8], off: usize) -> &str {
+    if off >= bytes.len() {
+        return "";
+    }
+    let end = bytes[off..]
+        .iter()
+        .position(|&b| b == 0)
+        .map_or(bytes.len(), |n| off + n);
+    std::str::from_utf8(&bytes[off..end]).unwrap_or("")
+}
+
+pub fn load_segments(bytes: &[u8]) -> Result<Vec<Segment>, &'static str> {
+    let le = check_header(bytes)?;
+
+    let e_phoff = read_u32(bytes, 28, le) as usize;
+    let e_phentsize = read_u16(bytes, 42, le) as usize;
+    let e_phnum = read_u16(bytes, 44, le) as usize;
+
+    let mut segments = Vec::new();
+    for i in 0..e_phnum {
+        let ph = e_phoff + i * e_phentsize;
+        if ph + 32 > bytes.len() {
+            return Err("Truncated program header");
+        }
+        if read_u32(bytes, ph, le) != PT_LOAD {
+            continue;
+        }
+
+        let p_offset = read_u32(bytes, ph + 4, le) as usize;
+        let p_paddr = read_u32(bytes, ph + 12, le);
+        let p_filesz = read_u32(bytes, ph + 16, le) as usize;
+        if p_filesz == 0 {
+            continue;
+        }
+        if p_offset + p_filesz > bytes.len() {
+            return Err("Truncated segment data");
+        }
+
+        segments.push(Segment {
+            addr: p_paddr,
+            data: bytes[p_offset..p_offset + p_filesz].to_vec(),
+        });
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod segment_tests {
+    use super::*;
+
+    const EHDR_SIZE: u32 = 52;
+    const PHDR_SIZE: u32 = 32;
+
+    fn set_header(bytes: &mut Vec<u8>, phoff: u32, phnum: u16) {
+        bytes[0..4].copy_from_slice(b"\x7fELF");
+        bytes[4] = 1; // 32-bit
+        bytes[5] = 1; // little-endian
+        bytes[28..32].copy_from_slice(&phoff.to_le_bytes());
+        bytes[42..44].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        bytes[44..46].copy_from_slice(&phnum.to_le_bytes());
+    }
+
+    fn program_header(p_type: u32, offset: u32, paddr: u32, filesz: u32) -> Vec<u8> {
+        let mut ph = vec![0u8; PHDR_SIZE as usize];
+        ph[0..4].copy_from_slice(&p_type.to_le_bytes());
+        ph[4..8].copy_from_slice(&offset.to_le_bytes());
+        ph[12..16].copy_from_slice(&paddr.to_le_bytes());
+        ph[16..20].copy_from_slice(&filesz.to_le_bytes());
+        ph
+    }
+
+    #[test]
+    fn loads_a_single_pt_load_segment() {
+        let phoff = EHDR_SIZE;
+        let data = b"hello!!!";
+        let data_off = phoff + PHDR_SIZE;
+        let mut bytes = vec![0u8; EHDR_SIZE as usize];
+        set_header(&mut bytes, phoff, 1);
+        bytes.extend(program_header(
+            PT_LOAD,
+            data_off,
+            0x0800_0000,
+            data.len() as u32,
+        ));
+        bytes.extend_from_slice(data);
+
+        let segments = load_segments(&bytes).unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].addr, 0x0800_0000);
+        assert_eq!(segments[0].data, data);
+    }
+
+    #[test]
+    fn skips_non_load_and_zero_length_segments() {
+        let phoff = EHDR_SIZE;
+        let mut bytes = vec![0u8; EHDR_SIZE as usize];
+        set_header(&mut bytes, phoff, 2);
+        bytes.extend(program_header(2, 0, 0, 0)); // not PT_LOAD
+        bytes.extend(program_header(PT_LOAD, 0, 0, 0)); // zero-length PT_LOAD
+        let segments = load_segments(&bytes).unwrap();
+        assert!(segments.is_empty());
+    }
+
+    #[test]
+    fn errors_on_a_truncated_program_header() {
+        let phoff = EHDR_SIZE;
+        let mut bytes = vec![0u8; EHDR_SIZE as usize];
+        set_header(&mut bytes, phoff, 1);
+        assert!(load_segments(&bytes).is_err());
+    }
+
+    #[test]
+    fn errors_on_truncated_segment_data() {
+        let phoff = EHDR_SIZE;
+        let mut bytes = vec![0u8; EHDR_SIZE as usize];
+        set_header(&mut bytes, phoff, 1);
+        bytes.extend(program_header(PT_LOAD, phoff + PHDR_SIZE, 0, 100));
+        assert!(load_segments(&bytes).is_err());
+    }
+
+    #[test]
+    fn errors_on_a_non_elf_file() {
+        assert!(load_segments(b"not an elf").is_err());
+    }
+}
+
+// Builds a tag -> format string table from the `.defmt` section of an ELF
+// image. defmt stores each log format string as the name of a symbol that
+// points into the `.defmt` section, with the symbol's value being the tag
+// used to reference it from an RTT frame; this walks the symbol table for
+// symbols in that section and indexes their names, as stored in the string
+// table (not demangled), by tag.
+pub fn load_defmt_table(bytes: &[u8]) -> Result<HashMap<u32, String>, &'static str> {
+    let le = check_header(bytes)?;
+
+    let e_shoff = read_u32(bytes, 32, le) as usize;
+    let e_shentsize = read_u16(bytes, 46, le) as usize;
+    let e_shnum = read_u16(bytes, 48, le) as usize;
+
+    let section = |i: usize| -> Result<usize, &'static str> {
+        let sh = e_shoff + i * e_shentsize;
+        if sh + 40 > bytes.len() {
+            return Err("Truncated section header");
+        }
+        Ok(sh)
+    };
+
+    let mut defmt_index = None;
+    let mut symtab_index = None;
+    for i in 0..e_shnum {
+        let sh = section(i)?;
+        if read_u32(bytes, sh + 4, le) == SHT_SYMTAB {
+            symtab_index = Some(i);
+        }
+    }
+
+    let shstrndx = read_u16(bytes, 50, le) as usize;
+    let shstrtab_sh = section(shstrndx)?;
+    let shstrtab_off = read_u32(bytes, shstrtab_sh + 16, le) as usize;
+
+    for i in 0..e_shnum {
+        let sh = section(i)?;
+        let name_off = read_u32(bytes, sh, le) as usize;
+        if read_cstr(bytes, shstrtab_off + name_off) == ".defmt" {
+            defmt_index = Some(i);
+        }
+    }
+
+    let defmt_index = defmt_index.ok_or("No .defmt section found in the ELF file")?;
+    let symtab_index = symtab_index.ok_or("No symbol table found in the ELF file")?;
+
+    let symtab_sh = section(symtab_index)?;
+    let symtab_off = read_u32(bytes, symtab_sh + 16, le) as usize;
+    let symtab_size = read_u32(bytes, symtab_sh + 20, le) as usize;
+    let symtab_entsize = read_u32(bytes, symtab_sh + 36, le) as usize;
+    if symtab_entsize == 0 {
+        return Err("Symbol table section has a zero entry size");
+    }
+    let strtab_index = read_u32(bytes, symtab_sh + 24, le) as usize;
+    let strtab_sh = section(strtab_index)?;
+    let strtab_off = read_u32(bytes, strtab_sh + 16, le) as usize;
+
+    let mut table = HashMap::new();
+    let num_syms = symtab_size / symtab_entsize;
+    for i in 0..num_syms {
+        let sym = symtab_off + i * symtab_entsize;
+        if sym + 16 > bytes.len() {
+            return Err("Truncated symbol table entry");
+        }
+        let st_name = read_u32(bytes, sym, le) as usize;
+        let st_value = read_u32(bytes, sym + 4, le);
+        let st_shndx = read_u16(bytes, sym + 14, le) as usize;
+        if st_shndx == defmt_index {
+            table.insert(st_value, read_cstr(bytes, strtab_off + st_name).to_string());
+        }
+    }
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod defmt_tests {
+    use super::*;
+
+    const EHDR_SIZE: u32 = 52;
+    const SHDR_SIZE: u32 = 40;
+    const SYM_SIZE: u32 = 16;
+
+    fn set_header(bytes: &mut Vec<u8>, shoff: u32, shnum: u16, shstrndx: u16) {
+        bytes[0..4].copy_from_slice(b"\x7fELF");
+        bytes[4] = 1; // 32-bit
+        bytes[5] = 1; // little-endian
+        bytes[32..36].copy_from_slice(&shoff.to_le_bytes());
+        bytes[46..48].copy_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+        bytes[48..50].copy_from_slice(&shnum.to_le_bytes());
+        bytes[50..52].copy_from_slice(&shstrndx.to_le_bytes());
+    }
+
+    fn section_header(
+        name_off: u32,
+        sh_type: u32,
+        offset: u32,
+        size: u32,
+        link: u32,
+        entsize: u32,
+    ) -> Vec<u8> {
+        let mut sh = vec![0u8; SHDR_SIZE as usize];
+        sh[0..4].copy_from_slice(&name_off.to_le_bytes());
+        sh[4..8].copy_from_slice(&sh_type.to_le_bytes());
+        sh[16..20].copy_from_slice(&offset.to_le_bytes());
+        sh[20..24].copy_from_slice(&size.to_le_bytes());
+        sh[24..28].copy_from_slice(&link.to_le_bytes());
+        sh[36..40].copy_from_slice(&entsize.to_le_bytes());
+        sh
+    }
+
+    // Builds a minimal ELF with a `.defmt` section and a symbol table whose
+    // entries (name, tag) point into it, for exercising `load_defmt_table`
+    // without a real firmware image.
+    fn build_defmt_elf(symbols: &[(&str, u32)]) -> Vec<u8> {
+        let mut shstrtab = vec![0u8];
+        let defmt_name_off = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".defmt\0");
+        let symtab_name_off = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".symtab\0");
+        let strtab_name_off = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".strtab\0");
+        let shstrtab_name_off = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".shstrtab\0");
+
+        let mut strtab = vec![0u8];
+        let sym_name_offs: Vec<u32> = symbols
+            .iter()
+            .map(|(name, _)| {
+                let off = strtab.len() as u32;
+                strtab.extend_from_slice(name.as_bytes());
+                strtab.push(0);
+                off
+            })
+            .collect();
+
+        let num_sections = 5u32; // NULL, .defmt, .symtab, .strtab, .shstrtab
+        let shoff = EHDR_SIZE;
+        let defmt_data = vec![0u8; 16];
+        let defmt_off = shoff + num_sections * SHDR_SIZE;
+        let symtab_off = defmt_off + defmt_data.len() as u32;
+        let symtab_size = symbols.len() as u32 * SYM_SIZE;
+        let strtab_off = symtab_off + symtab_size;
+        let shstrtab_off = strtab_off + strtab.len() as u32;
+
+        let mut bytes = vec![0u8; EHDR_SIZE as usize];
+        set_header(&mut bytes, shoff, num_sections as u16, 4);
+
+        bytes.extend(vec![0u8; SHDR_SIZE as usize]); // 0: NULL
+        bytes.extend(section_header(
+            defmt_name_off,
+            0,
+            defmt_off,
+            defmt_data.len() as u32,
+            0,
+            0,
+        )); // 1: .defmt
+        bytes.extend(section_header(
+            symtab_name_off,
+            SHT_SYMTAB,
+            symtab_off,
+            symtab_size,
+            3,
+            SYM_SIZE,
+        )); // 2: .symtab, sh_link -> .strtab (section 3)
+        bytes.extend(section_header(
+            strtab_name_off,
+            0,
+            strtab_off,
+            strtab.len() as u32,
+            0,
+            0,
+        )); // 3: .strtab
+        bytes.extend(section_header(
+            shstrtab_name_off,
+            0,
+            shstrtab_off,
+            shstrtab.len() as u32,
+            0,
+            0,
+        )); // 4: .shstrtab
+
+        bytes.extend(defmt_data);
+        for (i, (_, tag)) in symbols.iter().enumerate() {
+            let mut sym = vec![0u8; SYM_SIZE as usize];
+            sym[0..4].copy_from_slice(&sym_name_offs[i].to_le_bytes());
+            sym[4..8].copy_from_slice(&tag.to_le_bytes());
+            sym[14..16].copy_from_slice(&1u16.to_le_bytes()); // st_shndx = .defmt's section index
+            bytes.extend(sym);
+        }
+        bytes.extend(strtab);
+        bytes.extend(shstrtab);
+        bytes
+    }
+
+    fn build_elf_without_defmt() -> Vec<u8> {
+        let mut shstrtab = vec![0u8];
+        let shstrtab_name_off = shstrtab.len() as u32;
+        shstrtab.extend_from_slice(b".shstrtab\0");
+
+        let num_sections = 2u32; // NULL, .shstrtab
+        let shoff = EHDR_SIZE;
+        let shstrtab_off = shoff + num_sections * SHDR_SIZE;
+
+        let mut bytes = vec![0u8; EHDR_SIZE as usize];
+        set_header(&mut bytes, shoff, num_sections as u16, 1);
+
+        bytes.extend(vec![0u8; SHDR_SIZE as usize]); // 0: NULL
+        bytes.extend(section_header(
+            shstrtab_name_off,
+            0,
+            shstrtab_off,
+            shstrtab.len() as u32,
+            0,
+            0,
+        )); // 1: .shstrtab
+        bytes.extend(shstrtab);
+        bytes
+    }
+
+    #[test]
+    fn loads_defmt_table_from_symbols_in_the_defmt_section() {
+        let bytes = build_defmt_elf(&[("Hello, {}!", 1), ("Goodbye", 2)]);
+        let table = load_defmt_table(&bytes).unwrap();
+        assert_eq!(table.get(&1).map(String::as_str), Some("Hello, {}!"));
+        assert_eq!(table.get(&2).map(String::as_str), Some("Goodbye"));
+    }
+
+    #[test]
+    fn errors_without_a_defmt_section() {
+        let bytes = build_elf_without_defmt();
+        assert!(load_defmt_table(&bytes).is_err());
+    }
+
+    #[test]
+    fn errors_on_a_truncated_elf_header() {
+        assert!(load_defmt_table(&[0u8; 10]).is_err());
+    }
+}
@@ -1,33 +1,160 @@
+mod config;
+mod elf;
+
+use ctrlc;
 use libusb;
 use stlink;
 
 use coresight::dap_access::DAPAccess;
+use coresight::jtag_access::JTAGAccess;
 use memory::MI;
 use probe::debug_probe::DebugProbe;
+use probe::protocol::WireProtocol;
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
+use config::{parse_config_command, Config, ConfigAction};
+use elf::Segment;
+
 enum REPLDisconnected {
-    Connect { n: u8 },
+    Config(ConfigAction),
+    Connect { n: u8, protocol: WireProtocol },
     Continue,
     Exit,
     Help,
 }
 
 enum REPLConnected {
+    Bin {
+        addr: u32,
+        path: String,
+    },
+    Config(ConfigAction),
     Continue,
     Disconnect,
-    Dump { loc: u32, words: u32 },
+    Dump {
+        loc: u32,
+        words: u32,
+    },
     Exit,
+    Flash {
+        path: String,
+    },
     Help,
+    Go,
+    Halt,
     Info,
     Reset,
+    Rtt {
+        start: u32,
+        len: u32,
+        elf_path: Option<String>,
+    },
+    Scan,
+    Step,
+    Trace {
+        channel: u8,
+        clock: u32,
+    },
+    Write {
+        addr: u32,
+        values: Vec<u32>,
+    },
+    Wreg {
+        bank: u16,
+        addr: u8,
+        value: u32,
+    },
+}
+
+// Remembers the last command line entered at the connected prompt, and how
+// many times in a row it's been repeated, so pressing Enter on an empty line
+// re-runs it - handy for stepping through code one `step` at a time.
+struct DebuggerState {
+    last_command: Option<String>,
+    repeat_count: u32,
+}
+
+impl DebuggerState {
+    fn new() -> Self {
+        DebuggerState {
+            last_command: None,
+            repeat_count: 0,
+        }
+    }
+}
+
+fn parse_protocol(s: &str) -> Option<WireProtocol> {
+    match s.to_lowercase().as_str() {
+        "swd" => Some(WireProtocol::Swd),
+        "jtag" => Some(WireProtocol::Jtag),
+        _ => None,
+    }
+}
+
+// Parses a hex number, with or without a leading "0x"/"0X" - the one
+// convention every command that takes a raw hex value should share.
+fn parse_hex(token: &str) -> Result<u32, String> {
+    let trimmed = token.trim_start_matches("0x").trim_start_matches("0X");
+    u32::from_str_radix(trimmed, 16)
+        .map_err(|_| format!("Cannot parse '{}' as a hex number", token))
+}
+
+// Aliases are checked before hex parsing, so an alias named after a hex
+// digit string (e.g. "dead") still resolves to the alias instead of 0xdead.
+fn parse_address(config: &Config, token: &str) -> Result<u32, String> {
+    if let Some(value) = config.get(&format!("alias.{}", token)) {
+        return parse_hex(value)
+            .map_err(|_| format!("Alias '{}' has an invalid address '{}'", token, value));
+    }
+    parse_hex(token).map_err(|_| format!("Cannot parse '{}' as an address or alias", token))
+}
+
+#[cfg(test)]
+mod address_parsing_tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_hex_with_and_without_0x_prefix() {
+        assert_eq!(parse_hex("0x1A"), Ok(0x1A));
+        assert_eq!(parse_hex("0X1a"), Ok(0x1A));
+        assert_eq!(parse_hex("1a"), Ok(0x1A));
+    }
+
+    #[test]
+    fn rejects_invalid_hex() {
+        assert!(parse_hex("not hex").is_err());
+    }
+
+    #[test]
+    fn resolves_a_plain_alias() {
+        let mut values = HashMap::new();
+        values.insert("alias.flash_base".to_string(), "0x08000000".to_string());
+        let config = Config::from_map(values);
+        assert_eq!(parse_address(&config, "flash_base"), Ok(0x0800_0000));
+    }
+
+    #[test]
+    fn prefers_an_alias_over_a_hex_digit_name() {
+        let mut values = HashMap::new();
+        values.insert("alias.dead".to_string(), "0x12345678".to_string());
+        let config = Config::from_map(values);
+        assert_eq!(parse_address(&config, "dead"), Ok(0x1234_5678));
+    }
+
+    #[test]
+    fn falls_back_to_hex_when_no_alias_matches() {
+        let config = Config::from_map(HashMap::new());
+        assert_eq!(parse_address(&config, "dead"), Ok(0xDEAD));
+    }
 }
 
 fn unconnected_repl(
     rl: &mut rustyline::Editor<()>,
     probe: &mut Option<impl DebugProbe>,
+    config: &Config,
 ) -> REPLDisconnected {
     let context = libusb::Context::new().unwrap();
     let plugged_devices = stlink::get_all_plugged_devices(&context);
@@ -40,14 +167,39 @@ fn unconnected_repl(
         Ok(line) => {
             rl.add_history_entry(line.as_ref());
             match line.split_whitespace().collect::<Vec<&str>>().split_first() {
+                Some((&"config", rest)) => match parse_config_command(rest) {
+                    Ok(action) => REPLDisconnected::Config(action),
+                    Err(e) => {
+                        println!("{}", e);
+                        REPLDisconnected::Continue
+                    }
+                },
                 Some((&"connect", rest)) => {
                     if !rest.is_empty() {
+                        let protocol = if rest.len() > 1 {
+                            match parse_protocol(rest[1]) {
+                                Some(protocol) => protocol,
+                                None => {
+                                    println!(
+                                        "Invalid protocol '{}', expected 'swd' or 'jtag'",
+                                        rest[1]
+                                    );
+                                    return REPLDisconnected::Continue;
+                                }
+                            }
+                        } else {
+                            config
+                                .get("default_protocol")
+                                .and_then(parse_protocol)
+                                .unwrap_or(WireProtocol::Swd)
+                        };
+
                         rest[0].parse::<u8>().ok().map_or_else(
                             || {
                                 println!("Invalid probe id '{}'", rest[0]);
                                 REPLDisconnected::Continue
                             },
-                            |n| REPLDisconnected::Connect { n },
+                            |n| REPLDisconnected::Connect { n, protocol },
                         )
                     } else {
                         println!("Need to supply probe id");
@@ -89,20 +241,82 @@ fn unconnected_repl(
 fn connected_repl(
     rl: &mut rustyline::Editor<()>,
     probe: &mut Option<impl DebugProbe>,
+    debugger: &mut DebuggerState,
+    config: &Config,
 ) -> REPLConnected {
     let context = libusb::Context::new().unwrap();
     let plugged_devices = stlink::get_all_plugged_devices(&context);
 
+    let repeat_suffix = if debugger.repeat_count > 0 {
+        format!(" (repeated {}x)", debugger.repeat_count)
+    } else {
+        String::new()
+    };
     let readline = rl.readline(&format!(
-        "{} >> ",
-        probe.as_ref().map_or("(Not connected)", |p| p.get_name())
+        "{}{} >> ",
+        probe.as_ref().map_or("(Not connected)", |p| p.get_name()),
+        repeat_suffix
     ));
     match readline {
         Ok(line) => {
-            rl.add_history_entry(line.as_ref());
+            let line = if line.trim().is_empty() {
+                match &debugger.last_command {
+                    Some(last) => {
+                        debugger.repeat_count += 1;
+                        last.clone()
+                    }
+                    None => line,
+                }
+            } else {
+                rl.add_history_entry(line.as_ref());
+                debugger.last_command = Some(line.clone());
+                debugger.repeat_count = 0;
+                line
+            };
             match line.split_whitespace().collect::<Vec<&str>>().split_first() {
+                Some((&"bin", rest)) if rest.len() == 2 => match parse_address(config, rest[0]) {
+                    Ok(addr) => REPLConnected::Bin {
+                        addr,
+                        path: rest[1].to_string(),
+                    },
+                    Err(e) => {
+                        println!("{}", e);
+                        REPLConnected::Continue
+                    }
+                },
+                Some((&"bin", _)) => {
+                    println!("Usage: bin <addr> <file>");
+                    REPLConnected::Continue
+                }
+                Some((&"config", rest)) => match parse_config_command(rest) {
+                    Ok(action) => REPLConnected::Config(action),
+                    Err(e) => {
+                        println!("{}", e);
+                        REPLConnected::Continue
+                    }
+                },
                 Some((&"disconnect", _)) => REPLConnected::Disconnect,
+                Some((&"flash", rest)) if rest.len() == 1 => REPLConnected::Flash {
+                    path: rest[0].to_string(),
+                },
+                Some((&"flash", _)) => {
+                    println!("Usage: flash <file.elf>");
+                    REPLConnected::Continue
+                }
                 Some((&"dump", rest)) => match rest.len() {
+                    0 => match config.get("default_dump_addr") {
+                        Some(v) => match parse_hex(v) {
+                            Ok(loc) => REPLConnected::Dump { loc, words: 1 },
+                            Err(_) => {
+                                println!("Stored default_dump_addr '{}' is not a valid address", v);
+                                REPLConnected::Continue
+                            }
+                        },
+                        None => {
+                            println!("Usage: dump <loc> [n]");
+                            REPLConnected::Continue
+                        }
+                    },
                     1..=2 => {
                         let words = if rest.len() == 2 {
                             rest[1].parse::<u32>().unwrap_or_else(|_| {
@@ -116,19 +330,21 @@ fn connected_repl(
                             1
                         };
 
-                        u32::from_str_radix(rest[0], 16).ok().map_or_else(
-                            || {
-                                println!("Cannot parse '{}' as address", rest[0]);
+                        match parse_address(config, rest[0]) {
+                            Ok(loc) => REPLConnected::Dump { loc, words },
+                            Err(e) => {
+                                println!("{}", e);
                                 REPLConnected::Continue
-                            },
-                            |loc| REPLConnected::Dump { loc, words },
-                        )
+                            }
+                        }
                     }
                     _ => {
                         println!("Usage: dump <loc> [n]");
                         REPLConnected::Continue
                     }
                 },
+                Some((&"go", _)) => REPLConnected::Go,
+                Some((&"halt", _)) => REPLConnected::Halt,
                 Some((&"help", _)) => REPLConnected::Help,
                 Some((&"info", _)) => REPLConnected::Info,
                 Some((&"list", _)) => match &plugged_devices {
@@ -148,6 +364,122 @@ fn connected_repl(
                     Err(_) => REPLConnected::Continue,
                 },
                 Some((&"reset", _)) => REPLConnected::Reset,
+                Some((&"rtt", rest)) => {
+                    let start = if !rest.is_empty() {
+                        match parse_address(config, rest[0]) {
+                            Ok(start) => start,
+                            Err(e) => {
+                                println!("{}", e);
+                                return REPLConnected::Continue;
+                            }
+                        }
+                    } else {
+                        DEFAULT_RTT_SCAN_START
+                    };
+                    let len = if rest.len() > 1 {
+                        match rest[1].parse::<u32>() {
+                            Ok(len) => len,
+                            Err(_) => {
+                                println!("Cannot parse '{}' as length", rest[1]);
+                                return REPLConnected::Continue;
+                            }
+                        }
+                    } else {
+                        DEFAULT_RTT_SCAN_LEN
+                    };
+                    let elf_path = rest.get(2).map(|s| s.to_string());
+                    REPLConnected::Rtt {
+                        start,
+                        len,
+                        elf_path,
+                    }
+                }
+                Some((&"scan", _)) => REPLConnected::Scan,
+                Some((&"step", _)) => REPLConnected::Step,
+                Some((&"trace", rest)) => {
+                    let channel = if !rest.is_empty() {
+                        match rest[0].parse::<u8>() {
+                            Ok(channel) => channel,
+                            Err(_) => {
+                                println!("Cannot parse '{}' as channel", rest[0]);
+                                return REPLConnected::Continue;
+                            }
+                        }
+                    } else {
+                        0
+                    };
+                    let clock = if rest.len() > 1 {
+                        match rest[1].parse::<u32>() {
+                            Ok(clock) => clock,
+                            Err(_) => {
+                                println!("Cannot parse '{}' as trace clock", rest[1]);
+                                return REPLConnected::Continue;
+                            }
+                        }
+                    } else {
+                        config
+                            .get("trace_clock")
+                            .and_then(|v| v.parse::<u32>().ok())
+                            .unwrap_or(DEFAULT_TRACE_CLOCK_HZ)
+                    };
+                    REPLConnected::Trace { channel, clock }
+                }
+                Some((&"write", rest)) if rest.len() >= 2 => match parse_address(config, rest[0]) {
+                    Ok(addr) => {
+                        let values: Result<Vec<u32>, String> =
+                            rest[1..].iter().map(|v| parse_hex(v)).collect();
+                        match values {
+                            Ok(values) => REPLConnected::Write { addr, values },
+                            Err(e) => {
+                                println!("{}", e);
+                                REPLConnected::Continue
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        println!("{}", e);
+                        REPLConnected::Continue
+                    }
+                },
+                Some((&"write", _)) => {
+                    println!("Usage: write <addr> <val>...");
+                    REPLConnected::Continue
+                }
+                Some((&"wreg", rest)) if rest.len() == 3 => {
+                    match (parse_hex(rest[0]), parse_hex(rest[1]), parse_hex(rest[2])) {
+                        (Ok(bank), Ok(addr), Ok(value)) => {
+                            if bank > u16::MAX as u32 {
+                                println!("Bank '{}' does not fit in 16 bits", rest[0]);
+                                REPLConnected::Continue
+                            } else if addr > u8::MAX as u32 {
+                                println!("Addr '{}' does not fit in 8 bits", rest[1]);
+                                REPLConnected::Continue
+                            } else {
+                                REPLConnected::Wreg {
+                                    bank: bank as u16,
+                                    addr: addr as u8,
+                                    value,
+                                }
+                            }
+                        }
+                        (bank, addr, value) => {
+                            if let Err(e) = bank {
+                                println!("Cannot parse bank: {}", e);
+                            }
+                            if let Err(e) = addr {
+                                println!("Cannot parse addr: {}", e);
+                            }
+                            if let Err(e) = value {
+                                println!("Cannot parse value: {}", e);
+                            }
+                            REPLConnected::Continue
+                        }
+                    }
+                }
+                Some((&"wreg", _)) => {
+                    println!("Usage: wreg <bank> <addr> <val>");
+                    REPLConnected::Continue
+                }
                 Some((&"exit", _)) | Some((&"quit", _)) => REPLConnected::Exit,
                 _ => {
                     println!("Sorry, I don't know what '{}' is, try 'help'?", line);
@@ -163,7 +495,22 @@ fn connected_repl(
     }
 }
 
-fn connect(n: u8) -> Option<stlink::STLink> {
+fn handle_config_action(config: &mut Config, action: ConfigAction) {
+    match action {
+        ConfigAction::Read(key) => match config.get(&key) {
+            Some(value) => println!("{} = {}", key, value),
+            None => println!("'{}' is not set", key),
+        },
+        ConfigAction::Write(key, value) => {
+            config.set(&key, &value).map_err(|e| println!("{}", e)).ok();
+        }
+        ConfigAction::Remove(key) => {
+            config.remove(&key).map_err(|e| println!("{}", e)).ok();
+        }
+    }
+}
+
+fn connect(n: u8, protocol: WireProtocol) -> Option<stlink::STLink> {
     stlink::STLink::new_from_connected(|mut devices| {
         if devices.len() <= n as usize {
             println!("The probe device with the given id '{}' was not found", n);
@@ -173,7 +520,7 @@ fn connect(n: u8) -> Option<stlink::STLink> {
         }
     })
     .map(|mut device| {
-        device.attach(probe::protocol::WireProtocol::Swd).ok();
+        device.attach(protocol).ok();
         device
     })
     .ok()
@@ -190,12 +537,55 @@ fn parse_target_id(value: u32) -> (u8, u16, u16, u8) {
     )
 }
 
-fn dump_memory(device: &mut stlink::STLink, loc: u32, words: u32) -> Result<(), &str> {
-    let mut data = vec![0 as u32; words as usize];
+// MEM-AP registers used to walk target memory a word at a time through the
+// DAP, auto-incrementing so the dump doesn't need to re-specify TAR for
+// every word.
+const MEM_AP: u16 = 0x0;
+const MEM_AP_CSW: u8 = 0x00;
+const MEM_AP_TAR: u8 = 0x04;
+const MEM_AP_DRW: u8 = 0x0C;
+const MEM_AP_CSW_SIZE_32: u32 = 0x2;
+const MEM_AP_CSW_ADDRINC_SINGLE: u32 = 1 << 4;
+// ADIv5 only guarantees single-address auto-increment within a 1KB-aligned
+// TAR window; crossing that boundary without reloading TAR is
+// implementation-defined and commonly wraps within the window instead of
+// continuing on to the next one.
+const MEM_AP_AUTOINC_WINDOW_WORDS: u32 = 0x400 / 4;
 
+fn dump_memory(device: &mut stlink::STLink, loc: u32, words: u32) -> Result<(), &str> {
     device
-        .read_block(loc, &mut data.as_mut_slice())
-        .or_else(|_| Err("Failed to read block from target"))?;
+        .write_register(
+            MEM_AP,
+            MEM_AP_CSW,
+            MEM_AP_CSW_SIZE_32 | MEM_AP_CSW_ADDRINC_SINGLE,
+        )
+        .or_else(|_| Err("Failed to set the MEM-AP CSW register"))?;
+
+    let mut data = Vec::with_capacity(words as usize);
+    let mut word = 0;
+    while word < words {
+        let window_addr = loc + 4 * word;
+        let window_words = std::cmp::min(
+            words - word,
+            MEM_AP_AUTOINC_WINDOW_WORDS - (window_addr / 4) % MEM_AP_AUTOINC_WINDOW_WORDS,
+        );
+
+        device
+            .write_register(MEM_AP, MEM_AP_TAR, window_addr)
+            .or_else(|_| Err("Failed to set the MEM-AP TAR register"))?;
+
+        let mut batch = Batch::new();
+        for _ in 0..window_words {
+            batch.read(MEM_AP, MEM_AP_DRW);
+        }
+        let window_data = batch.flush(device).or_else(|(i, reason)| {
+            println!("Batch command {} failed: {}", i, reason);
+            Err("Failed to read block from target")
+        })?;
+        data.extend(window_data);
+
+        word += window_words;
+    }
 
     for word in 0..words {
         if word % 4 == 0 {
@@ -216,6 +606,185 @@ fn dump_memory(device: &mut stlink::STLink, loc: u32, words: u32) -> Result<(),
     Ok(())
 }
 
+// A queue of DAP register accesses; `flush` groups consecutive same-port
+// reads/writes into one `read_register_block`/`write_register_block` call
+// instead of one USB transaction per command.
+enum BatchCommand {
+    Read(u16, u8),
+    Write(u16, u8, u32),
+}
+
+// A maximal run of consecutive same-port `BatchCommand`s of one kind, plus
+// its starting index in the original queue.
+enum BatchRun {
+    Read {
+        start: usize,
+        port: u16,
+        addrs: Vec<u8>,
+    },
+    Write {
+        start: usize,
+        port: u16,
+        writes: Vec<(u8, u32)>,
+    },
+}
+
+// Splits `commands` into maximal runs of consecutive same-port reads (or
+// writes), so each run can be submitted as a single underlying transaction.
+fn group_batch(commands: &[BatchCommand]) -> Vec<BatchRun> {
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < commands.len() {
+        match &commands[i] {
+            BatchCommand::Read(port, _) => {
+                let port = *port;
+                let start = i;
+                let mut addrs = Vec::new();
+                while let Some(BatchCommand::Read(p, addr)) = commands.get(i) {
+                    if *p != port {
+                        break;
+                    }
+                    addrs.push(*addr);
+                    i += 1;
+                }
+                runs.push(BatchRun::Read { start, port, addrs });
+            }
+            BatchCommand::Write(port, _, _) => {
+                let port = *port;
+                let start = i;
+                let mut writes = Vec::new();
+                while let Some(BatchCommand::Write(p, addr, value)) = commands.get(i) {
+                    if *p != port {
+                        break;
+                    }
+                    writes.push((*addr, *value));
+                    i += 1;
+                }
+                runs.push(BatchRun::Write {
+                    start,
+                    port,
+                    writes,
+                });
+            }
+        }
+    }
+    runs
+}
+
+struct Batch {
+    queue: Vec<BatchCommand>,
+}
+
+impl Batch {
+    fn new() -> Self {
+        Batch { queue: Vec::new() }
+    }
+
+    fn read(&mut self, port: u16, addr: u8) {
+        self.queue.push(BatchCommand::Read(port, addr));
+    }
+
+    fn write(&mut self, port: u16, addr: u8, value: u32) {
+        self.queue.push(BatchCommand::Write(port, addr, value));
+    }
+
+    // Returns Read results in order. On failure, the index is the first
+    // command of the failing run, not necessarily the exact command, since
+    // a run fails or succeeds as a unit.
+    fn flush(&mut self, device: &mut stlink::STLink) -> Result<Vec<u32>, (usize, &'static str)> {
+        let mut results = Vec::new();
+        let commands: Vec<BatchCommand> = self.queue.drain(..).collect();
+        for run in group_batch(&commands) {
+            match run {
+                BatchRun::Read { start, port, addrs } => {
+                    let values = device
+                        .read_register_block(port, &addrs)
+                        .map_err(|_| (start, "Failed to read register block"))?;
+                    results.extend(values);
+                }
+                BatchRun::Write {
+                    start,
+                    port,
+                    writes,
+                } => {
+                    device
+                        .write_register_block(port, &writes)
+                        .map_err(|_| (start, "Failed to write register block"))?;
+                }
+            }
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+
+    #[test]
+    fn groups_consecutive_same_port_reads() {
+        let commands = vec![
+            BatchCommand::Read(1, 0x4),
+            BatchCommand::Read(1, 0x8),
+            BatchCommand::Read(2, 0x4),
+        ];
+        let runs = group_batch(&commands);
+        assert_eq!(runs.len(), 2);
+        match &runs[0] {
+            BatchRun::Read { start, port, addrs } => {
+                assert_eq!(*start, 0);
+                assert_eq!(*port, 1);
+                assert_eq!(addrs, &[0x4, 0x8]);
+            }
+            _ => panic!("expected a read run"),
+        }
+        match &runs[1] {
+            BatchRun::Read { start, port, addrs } => {
+                assert_eq!(*start, 2);
+                assert_eq!(*port, 2);
+                assert_eq!(addrs, &[0x4]);
+            }
+            _ => panic!("expected a read run"),
+        }
+    }
+
+    #[test]
+    fn splits_on_command_kind_change() {
+        let commands = vec![
+            BatchCommand::Read(1, 0x4),
+            BatchCommand::Write(1, 0x8, 0xAA),
+            BatchCommand::Read(1, 0x4),
+        ];
+        let runs = group_batch(&commands);
+        assert_eq!(runs.len(), 3);
+        assert!(matches!(runs[0], BatchRun::Read { start: 0, .. }));
+        assert!(matches!(runs[1], BatchRun::Write { start: 1, .. }));
+        assert!(matches!(runs[2], BatchRun::Read { start: 2, .. }));
+    }
+
+    #[test]
+    fn groups_consecutive_same_port_writes() {
+        let commands = vec![
+            BatchCommand::Write(3, 0x4, 0x1111),
+            BatchCommand::Write(3, 0x8, 0x2222),
+        ];
+        let runs = group_batch(&commands);
+        assert_eq!(runs.len(), 1);
+        match &runs[0] {
+            BatchRun::Write {
+                start,
+                port,
+                writes,
+            } => {
+                assert_eq!(*start, 0);
+                assert_eq!(*port, 3);
+                assert_eq!(writes, &[(0x4, 0x1111), (0x8, 0x2222)]);
+            }
+            _ => panic!("expected a write run"),
+        }
+    }
+}
+
 fn show_info(device: &mut stlink::STLink) -> Result<(), &str> {
     let version = device
         .get_version()
@@ -225,20 +794,23 @@ fn show_info(device: &mut stlink::STLink) -> Result<(), &str> {
     println!("Hardware Version: {:?}", version.0);
     println!("JTAG Version: {:?}", version.1);
 
-    device
-        .write_register(0xFFFF, 0x2, 0x2)
-        .or_else(|_| Err(""))?;
+    let mut batch = Batch::new();
+    batch.write(0xFFFF, 0x2, 0x2);
+    batch.read(0xFFFF, 0x4);
+    batch.read(0xFFFF, 0x0);
+    let results = batch.flush(device).or_else(|(i, reason)| {
+        println!("Batch command {} failed: {}", i, reason);
+        Err("Failed to read target identification registers")
+    })?;
 
-    let target_info = device.read_register(0xFFFF, 0x4).or_else(|_| Err(""))?;
-    let target_info = parse_target_id(target_info);
+    let target_info = parse_target_id(results[0]);
     println!("Target Identification Register (TARGETID):");
     println!(
         "\tRevision = {}, Part Number = {}, Designer = {}",
         target_info.0, target_info.3, target_info.2
     );
 
-    let target_info = device.read_register(0xFFFF, 0x0).or_else(|_| Err(""))?;
-    let target_info = parse_target_id(target_info);
+    let target_info = parse_target_id(results[1]);
     println!("\nIdentification Code Register (IDCODE):");
     println!(
         "\tProtocol = {},\n\tPart Number = {},\n\tJEDEC Manufacturer ID = {:x}",
@@ -262,14 +834,530 @@ fn show_info(device: &mut stlink::STLink) -> Result<(), &str> {
     Ok(())
 }
 
+// Upper bound on the number of TAPs we'll walk before giving up; a real
+// chain is almost always 1-4 devices, so this just needs enough headroom
+// that the all-1s flush tail is never mistaken for more IDCODE-bearing TAPs.
+const SCAN_CHAIN_MAX_TAPS: usize = 8;
+
+// After a TAP reset every compliant TAP defaults to IDCODE, so just shifting
+// DR is enough: an IDCODE TAP contributes a 32-bit word with bit 0 set, a
+// BYPASS TAP contributes a single 0 bit.
+fn scan_chain(device: &mut stlink::STLink) -> Result<Vec<(u8, u16, u16, u8)>, &'static str> {
+    let scan_bits = SCAN_CHAIN_MAX_TAPS * 32 + 32;
+
+    device
+        .jtag_tap_reset()
+        .or_else(|_| Err("Failed to reset the JTAG TAPs"))?;
+
+    let tdo = device
+        .shift_dr(&vec![true; scan_bits])
+        .or_else(|_| Err("Failed to shift the JTAG DR chain"))?;
+
+    let mut idcodes = Vec::new();
+    let mut i = 0;
+    while i + 32 <= tdo.len() && idcodes.len() < SCAN_CHAIN_MAX_TAPS {
+        if !tdo[i] {
+            // Single BYPASS bit contributed by a TAP with no IDCODE.
+            i += 1;
+            continue;
+        }
+        if tdo[i..i + 32].iter().all(|&bit| bit) {
+            // The all-1s flush tail; no more TAPs follow.
+            break;
+        }
+
+        let mut value: u32 = 0;
+        for (bit_index, &bit) in tdo[i..i + 32].iter().enumerate() {
+            if bit {
+                value |= 1 << bit_index;
+            }
+        }
+        idcodes.push(parse_target_id(value));
+        i += 32;
+    }
+
+    Ok(idcodes)
+}
+
 fn reset(device: &mut stlink::STLink) -> Result<(), &str> {
     device.target_reset().ok();
     Ok(())
 }
 
+fn write_memory(
+    device: &mut stlink::STLink,
+    addr: u32,
+    values: &[u32],
+) -> Result<(), &'static str> {
+    device
+        .write_block(addr, values)
+        .or_else(|_| Err("Failed to write block to target"))
+}
+
+fn write_dap_register(
+    device: &mut stlink::STLink,
+    bank: u16,
+    addr: u8,
+    value: u32,
+) -> Result<(), &str> {
+    device
+        .write_register(bank, addr, value)
+        .or_else(|_| Err("Failed to write register"))
+}
+
+const DHCSR: u32 = 0xE000_EDF0;
+const DHCSR_DBGKEY: u32 = 0xA05F_0000;
+const DHCSR_C_DEBUGEN: u32 = 1 << 0;
+const DHCSR_C_HALT: u32 = 1 << 1;
+const DHCSR_C_STEP: u32 = 1 << 2;
+
+fn halt(device: &mut stlink::STLink) -> Result<(), &str> {
+    write_word(device, DHCSR, DHCSR_DBGKEY | DHCSR_C_DEBUGEN | DHCSR_C_HALT)
+}
+
+fn step(device: &mut stlink::STLink) -> Result<(), &str> {
+    // C_STEP only takes effect while C_HALT is set in the same write; a
+    // write with C_STEP alone leaves the core free-running.
+    write_word(
+        device,
+        DHCSR,
+        DHCSR_DBGKEY | DHCSR_C_DEBUGEN | DHCSR_C_HALT | DHCSR_C_STEP,
+    )
+}
+
+fn go(device: &mut stlink::STLink) -> Result<(), &str> {
+    write_word(device, DHCSR, DHCSR_DBGKEY | DHCSR_C_DEBUGEN)
+}
+
+// STM32-style flash controller registers/bits; good enough for the common
+// case of a single flash bank starting at 0x0800_0000.
+const FLASH_KEYR: u32 = 0x4002_2004;
+const FLASH_SR: u32 = 0x4002_200C;
+const FLASH_CR: u32 = 0x4002_2010;
+const FLASH_AR: u32 = 0x4002_2014;
+const FLASH_KEY1: u32 = 0x4567_0123;
+const FLASH_KEY2: u32 = 0xCDEF_89AB;
+const FLASH_SR_BSY: u32 = 1 << 0;
+const FLASH_CR_PG: u32 = 1 << 0;
+const FLASH_CR_PER: u32 = 1 << 1;
+const FLASH_CR_STRT: u32 = 1 << 6;
+const FLASH_PAGE_SIZE: u32 = 1024;
+
+// Each poll is a full round trip to the probe, so this is sized in polls
+// rather than wall-clock time: comfortably longer than any real page
+// erase/program takes, but not an effectively-infinite wait if the
+// controller is actually stuck.
+const FLASH_IDLE_MAX_POLLS: u32 = 10_000;
+
+fn wait_flash_idle(device: &mut stlink::STLink) -> Result<(), &'static str> {
+    for _ in 0..FLASH_IDLE_MAX_POLLS {
+        if read_word(device, FLASH_SR)? & FLASH_SR_BSY == 0 {
+            return Ok(());
+        }
+    }
+    Err("Timed out waiting for the flash controller to go idle")
+}
+
+fn unlock_flash(device: &mut stlink::STLink) -> Result<(), &'static str> {
+    write_word(device, FLASH_KEYR, FLASH_KEY1)?;
+    write_word(device, FLASH_KEYR, FLASH_KEY2)
+}
+
+fn erase_page(device: &mut stlink::STLink, page_addr: u32) -> Result<(), &'static str> {
+    write_word(device, FLASH_CR, FLASH_CR_PER)?;
+    write_word(device, FLASH_AR, page_addr)?;
+    write_word(device, FLASH_CR, FLASH_CR_PER | FLASH_CR_STRT)?;
+    // Clear PER/STRT even if the wait below times out, so a stuck BSY bit
+    // doesn't leave the controller parked mid-erase for whatever comes next.
+    let idle = wait_flash_idle(device);
+    write_word(device, FLASH_CR, 0)?;
+    idle
+}
+
+fn pages_for_segment(addr: u32, len: u32) -> Vec<u32> {
+    let first_page = addr - addr % FLASH_PAGE_SIZE;
+    let mut pages = Vec::new();
+    let mut page = first_page;
+    while page < addr + len {
+        pages.push(page);
+        page += FLASH_PAGE_SIZE;
+    }
+    pages
+}
+
+// Pads the final word with 0xFF (erased-flash state) rather than zero, so
+// a neighboring segment whose data starts inside that same word isn't
+// blocked by already-programmed zero bits it didn't ask for.
+fn to_words(data: &[u8]) -> Vec<u32> {
+    data.chunks(4)
+        .map(|chunk| {
+            let mut word = [0xffu8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_le_bytes(word)
+        })
+        .collect()
+}
+
+// Erases every page spanning any segment exactly once, so two segments
+// sharing a flash page don't have one erase the other's already-written
+// bytes, then programs and verifies each segment in turn.
+fn flash_segments(device: &mut stlink::STLink, segments: &[Segment]) -> Result<(), String> {
+    halt(device)?;
+    unlock_flash(device)?;
+
+    let mut erased = std::collections::HashSet::new();
+    for segment in segments {
+        for page in pages_for_segment(segment.addr, segment.data.len() as u32) {
+            if erased.insert(page) {
+                erase_page(device, page)?;
+            }
+        }
+    }
+
+    for (n, segment) in segments.iter().enumerate() {
+        println!(
+            "Flashing segment {} at 0x{:08x} ({} bytes)",
+            n,
+            segment.addr,
+            segment.data.len()
+        );
+
+        let words = to_words(&segment.data);
+        write_word(device, FLASH_CR, FLASH_CR_PG)?;
+        write_memory(device, segment.addr, &words)?;
+        write_word(device, FLASH_CR, 0)?;
+
+        let mut readback = vec![0u32; words.len()];
+        device
+            .read_block(segment.addr, &mut readback)
+            .or_else(|_| Err("Failed to read back flashed segment"))?;
+
+        for (i, (expected, actual)) in words.iter().zip(readback.iter()).enumerate() {
+            if expected != actual {
+                return Err(format!(
+                    "Verify failed at 0x{:08x}: expected 0x{:08x}, got 0x{:08x}",
+                    segment.addr + 4 * i as u32,
+                    expected,
+                    actual
+                ));
+            }
+        }
+    }
+
+    reset(device)?;
+    go(device)?;
+    Ok(())
+}
+
+const CORE_DEMCR: u32 = 0xE000_EDFC;
+const CORE_DEMCR_TRCENA: u32 = 1 << 24;
+const TPIU_SPPR: u32 = 0xE004_00F0;
+const TPIU_SPPR_NRZ: u32 = 0x2;
+const TPIU_ACPR: u32 = 0xE004_0010;
+const ITM_TER: u32 = 0xE000_0E00;
+const ITM_TCR: u32 = 0xE000_0E80;
+const ITM_TCR_ITMENA: u32 = 1;
+const ITM_LAR: u32 = 0xE000_0FB0;
+const ITM_LAR_UNLOCK: u32 = 0xC5AC_CE55;
+const DEFAULT_TRACE_CLOCK_HZ: u32 = 16_000_000;
+const DEFAULT_SWO_BAUD_HZ: u32 = 2_000_000;
+
+const RTT_ID: &[u8; 16] = b"SEGGER RTT\0\0\0\0\0\0";
+const RTT_CHANNEL_DESCRIPTOR_SIZE: u32 = 24;
+// Typical Cortex-M SRAM window; covers the common case where the caller
+// doesn't know exactly where the target's RTT control block ended up.
+const DEFAULT_RTT_SCAN_START: u32 = 0x2000_0000;
+const DEFAULT_RTT_SCAN_LEN: u32 = 0x0002_0000;
+
+struct RttChannel {
+    buffer: u32,
+    size: u32,
+    write_offset: u32,
+    read_offset: u32,
+}
+
+// Scans `[start, start + len)` for the 16-byte "SEGGER RTT" control block ID
+// and returns its address.
+fn find_rtt_control_block(
+    device: &mut stlink::STLink,
+    start: u32,
+    len: u32,
+) -> Result<u32, &'static str> {
+    let mut data = vec![0u32; (len / 4) as usize];
+    device
+        .read_block(start, &mut data)
+        .or_else(|_| Err("Failed to scan RAM for the RTT control block"))?;
+
+    let bytes: Vec<u8> = data.iter().flat_map(|word| word.to_le_bytes()).collect();
+    bytes
+        .windows(RTT_ID.len())
+        .position(|window| window == RTT_ID)
+        .map(|offset| start + offset as u32)
+        .ok_or("Could not find a SEGGER RTT control block in the given range")
+}
+
+// Reads the up-channel descriptor at `channel` from the control block at
+// `cb_addr`. The control block layout is: 16-byte ID, u32 up-channel count,
+// u32 down-channel count, then `max(up, down)` channel descriptors of
+// (name ptr, buffer ptr, size, write offset, read offset, flags) u32s each.
+fn read_rtt_channel(
+    device: &mut stlink::STLink,
+    cb_addr: u32,
+    channel: u32,
+) -> Result<RttChannel, &'static str> {
+    let num_up = read_word(device, cb_addr + 16)?;
+    if channel >= num_up {
+        return Err("No such RTT up-channel");
+    }
+
+    let descriptor = cb_addr + 24 + channel * RTT_CHANNEL_DESCRIPTOR_SIZE;
+    let size = read_word(device, descriptor + 8)?;
+    let write_offset = read_word(device, descriptor + 12)?;
+    let read_offset = read_word(device, descriptor + 16)?;
+    // A zero size (an uninitialized descriptor, or a spurious ID match) would
+    // make the caller's ring-buffer wraparound divide by zero; out-of-range
+    // offsets are just as clear a sign the descriptor isn't one we trust.
+    if size == 0 || write_offset >= size || read_offset >= size {
+        return Err("RTT channel descriptor has an invalid size or offset");
+    }
+    Ok(RttChannel {
+        buffer: read_word(device, descriptor + 4)?,
+        size,
+        write_offset,
+        read_offset,
+    })
+}
+
+fn print_hex_bytes(data: &[u8]) {
+    for byte in data {
+        print!("{:02x} ", byte);
+    }
+    println!();
+}
+
+// Decodes a ULEB128-encoded unsigned integer from the start of `data`,
+// returning its value and how many bytes it consumed, or `None` if `data`
+// ends mid-encoding. Bounded to 10 bytes (enough for a full u64) so a
+// corrupted/garbled byte stream can't shift-overflow.
+fn read_leb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value: u64 = 0;
+    for (i, &byte) in data.iter().enumerate().take(10) {
+        value |= ((byte & 0x7F) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+// Treats the frame as one ULEB128 tag plus trailing hex, never reinterpreting
+// argument bytes as further tags - their width depends on the unparsed format
+// string's placeholders, and guessing wrong would desync every tag after it.
+fn decode_defmt_frame(data: &[u8], table: &std::collections::HashMap<u32, String>) {
+    if data.is_empty() {
+        return;
+    }
+    match read_leb128(data) {
+        Some((tag, consumed)) => {
+            match table.get(&(tag as u32)) {
+                Some(format) => print!("{}", format),
+                None => print!("<unknown defmt tag {}>", tag),
+            }
+            let args = &data[consumed..];
+            if !args.is_empty() {
+                print!(" (args:");
+                for byte in args {
+                    print!(" {:02x}", byte);
+                }
+                print!(")");
+            }
+            println!();
+        }
+        None => print_hex_bytes(data),
+    }
+}
+
+// Without a defmt table, frames are printed as hex rather than text, since
+// they're defmt's binary wire format, not a printable log line.
+fn rtt_monitor(
+    device: &mut stlink::STLink,
+    start: u32,
+    len: u32,
+    defmt_table: Option<&std::collections::HashMap<u32, String>>,
+) -> Result<(), &'static str> {
+    let channel = 0;
+    let cb_addr = find_rtt_control_block(device, start, len)?;
+    println!("Found RTT control block at 0x{:08x}", cb_addr);
+
+    let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let handler_running = running.clone();
+    ctrlc::set_handler(move || {
+        handler_running.store(false, std::sync::atomic::Ordering::SeqCst);
+    })
+    .ok();
+
+    while running.load(std::sync::atomic::Ordering::SeqCst) {
+        let rtt_channel = read_rtt_channel(device, cb_addr, channel)?;
+        let descriptor = cb_addr + 24 + channel * RTT_CHANNEL_DESCRIPTOR_SIZE;
+
+        if rtt_channel.write_offset != rtt_channel.read_offset {
+            // The buffer is walked with one `read_word` per byte offset
+            // (RTT's buffer offsets are byte offsets); only the low byte of
+            // each word read is used.
+            let mut offset = rtt_channel.read_offset;
+            let mut frame = Vec::new();
+            while offset != rtt_channel.write_offset {
+                frame.push(read_word(device, rtt_channel.buffer + offset)? as u8);
+                offset = (offset + 1) % rtt_channel.size;
+            }
+
+            match defmt_table {
+                Some(table) => decode_defmt_frame(&frame, table),
+                None => print_hex_bytes(&frame),
+            }
+
+            write_word(device, descriptor + 16, rtt_channel.write_offset)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn read_word(device: &mut stlink::STLink, addr: u32) -> Result<u32, &'static str> {
+    let mut data = [0u32; 1];
+    device
+        .read_block(addr, &mut data)
+        .or_else(|_| Err("Failed to read target memory"))?;
+    Ok(data[0])
+}
+
+fn write_word(device: &mut stlink::STLink, addr: u32, value: u32) -> Result<(), &'static str> {
+    device
+        .write_block(addr, &[value])
+        .or_else(|_| Err("Failed to write target memory"))
+}
+
+// Restores DEMCR when streaming is stopped with Ctrl-C. An error (a trace
+// clock below the SWO baud rate, a failed register access or SWO read)
+// returns early and leaves DEMCR with TRCENA set. The TPIU/ITM registers are
+// left as configured, since a subsequent trace just reprograms them anyway.
+fn trace_target(device: &mut stlink::STLink, channel: u8, clock: u32) -> Result<(), &'static str> {
+    let demcr = read_word(device, CORE_DEMCR)?;
+    write_word(device, CORE_DEMCR, demcr | CORE_DEMCR_TRCENA)?;
+
+    write_word(device, TPIU_SPPR, TPIU_SPPR_NRZ)?;
+    if clock < DEFAULT_SWO_BAUD_HZ {
+        return Err("Core clock is lower than the SWO baud rate");
+    }
+    let prescaler = clock / DEFAULT_SWO_BAUD_HZ - 1;
+    write_word(device, TPIU_ACPR, prescaler)?;
+
+    write_word(device, ITM_LAR, ITM_LAR_UNLOCK)?;
+    write_word(device, ITM_TER, 1 << channel)?;
+    write_word(device, ITM_TCR, ITM_TCR_ITMENA)?;
+
+    println!(
+        "Tracing stimulus port {} (SWO clock {} Hz), press Ctrl-C to stop",
+        channel, clock
+    );
+
+    let running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let handler_running = running.clone();
+    ctrlc::set_handler(move || {
+        handler_running.store(false, std::sync::atomic::Ordering::SeqCst);
+    })
+    .ok();
+
+    while running.load(std::sync::atomic::Ordering::SeqCst) {
+        let packet = device
+            .read_swo()
+            .or_else(|_| Err("Failed to read SWO data from probe"))?;
+        for (port, payload) in decode_itm_packets(&packet) {
+            if port == 0 {
+                for &byte in &payload {
+                    print!("{}", byte as char);
+                }
+            } else {
+                print!("[port {}]", port);
+                for &byte in &payload {
+                    print!(" {:02x}", byte);
+                }
+                println!();
+            }
+        }
+    }
+
+    write_word(device, CORE_DEMCR, demcr)?;
+    Ok(())
+}
+
+// Decodes a run of ITM software-source packets into (port, payload) pairs:
+// the header byte's low two bits give the payload size (01 = 1 byte, 10 = 2
+// bytes, 11 = 4 bytes) and bits [7:3] give the stimulus port. Hardware-source
+// packets are skipped, not decoded, but their payload still has to be
+// consumed so the next header is read from the right offset.
+fn decode_itm_packets(data: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut packets = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let header = data[i];
+        i += 1;
+
+        let size = match header & 0x3 {
+            0x1 => 1,
+            0x2 => 2,
+            0x3 => 4,
+            _ => continue,
+        };
+        if i + size > data.len() {
+            break;
+        }
+        if header & 0x4 != 0 {
+            i += size;
+            continue;
+        }
+
+        let port = header >> 3;
+        packets.push((port, data[i..i + size].to_vec()));
+        i += size;
+    }
+    packets
+}
+
+#[cfg(test)]
+mod itm_tests {
+    use super::*;
+
+    #[test]
+    fn decodes_a_single_byte_packet() {
+        let data = [0x01, 0x41];
+        assert_eq!(decode_itm_packets(&data), vec![(0, vec![0x41])]);
+    }
+
+    #[test]
+    fn decodes_port_from_header_bits() {
+        let data = [0x0A, 0x41, 0x42];
+        assert_eq!(decode_itm_packets(&data), vec![(1, vec![0x41, 0x42])]);
+    }
+
+    #[test]
+    fn skips_hardware_packets_without_desyncing() {
+        let data = [0x05, 0xFF, 0x01, 0x41];
+        assert_eq!(decode_itm_packets(&data), vec![(0, vec![0x41])]);
+    }
+
+    #[test]
+    fn stops_on_a_truncated_trailing_packet() {
+        let data = [0x01, 0x41, 0x03, 0x01];
+        assert_eq!(decode_itm_packets(&data), vec![(0, vec![0x41])]);
+    }
+}
+
 fn main() {
     let mut probe: Option<stlink::STLink> = None;
     let mut rl = Editor::<()>::new();
+    let mut debugger = DebuggerState::new();
+    let mut config = Config::load();
 
     println!("Probemeister at your service!");
 
@@ -277,52 +1365,186 @@ fn main() {
 
     loop {
         match &mut probe {
-            None => match unconnected_repl(&mut rl, &mut probe) {
-                REPLDisconnected::Help => {
+            None => {
+                match unconnected_repl(&mut rl, &mut probe, &config) {
+                    REPLDisconnected::Help => {
+                        println!("The following commands are available:");
+                        println!("\tconfig read|write|remove <key> [value]\t- manage persistent settings");
+                        println!("\tconnect <n> [swd|jtag]\t- connect to a debugging probe (STLink only for now)");
+                        println!("\texit\t\t- exit");
+                        println!("\tquit\t\t- exit");
+                    }
+                    REPLDisconnected::Config(action) => handle_config_action(&mut config, action),
+                    REPLDisconnected::Connect { n, protocol } => {
+                        probe = connect(n, protocol);
+                    }
+                    REPLDisconnected::Exit => break,
+                    REPLDisconnected::Continue => (),
+                }
+            }
+            Some(_) => match connected_repl(&mut rl, &mut probe, &mut debugger, &config) {
+                REPLConnected::Help => {
                     println!("The following commands are available:");
-                    println!("\tconnect <n>\t- connect to a debugging probe (STLink only for now)");
+                    println!("\tbin <addr> <file>\t- flash a raw binary image at addr");
+                    println!(
+                        "\tconfig read|write|remove <key> [value]\t- manage persistent settings"
+                    );
+                    println!("\tdisconnect\t- disconnect from a debugging probe");
+                    println!(
+                        "\tdump <loc> [n]\t- dump n words of data at address loc from the target"
+                    );
                     println!("\texit\t\t- exit");
+                    println!("\tflash <file.elf>\t- flash an ELF image's loadable segments");
+                    println!("\tgo\t\t- resume a halted target");
+                    println!("\thalt\t\t- halt the target core");
+                    println!("\tinfo\t\t- show information about connected probe");
                     println!("\tquit\t\t- exit");
+                    println!("\treset\t\t- reset the target");
+                    println!(
+                        "\trtt [start] [len] [elf]\t- monitor defmt-over-RTT logs from the target, decoding via elf's .defmt table if given"
+                    );
+                    println!(
+                        "\tscan\t\t- enumerate the IDCODEs of the TAPs on the JTAG scan chain"
+                    );
+                    println!("\tstep\t\t- single-step the target core");
+                    println!(
+                        "\ttrace [channel] [clock]\t- stream SWO/ITM trace output (Ctrl-C to stop)"
+                    );
+                    println!("\twrite <addr> <val>...\t- write words to memory at addr");
+                    println!("\twreg <bank> <addr> <val>\t- write a DAP register");
+                    println!("\t<enter>\t\t- repeat the last command");
+                    println!(
+                        "\tAddresses accept a config alias in place of a hex value, e.g. 'config write alias.flash_base 0x08000000'"
+                    );
                 }
-                REPLDisconnected::Connect { n } => {
-                    probe = connect(n);
+                REPLConnected::Info => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        show_info(&mut probe).ok();
+                    }
                 }
-                REPLDisconnected::Exit => break,
-                REPLDisconnected::Continue => (),
-            },
-            Some(_) => {
-                match connected_repl(&mut rl, &mut probe) {
-                    REPLConnected::Help => {
-                        println!("The following commands are available:");
-                        println!("\tdisconnect\t- disconnect from a debugging probe");
-                        println!("\tdump <loc> [n]\t- dump n words of data at address loc from the target");
-                        println!("\texit\t\t- exit");
-                        println!("\tinfo\t\t- show information about connected probe");
-                        println!("\tquit\t\t- exit");
-                        println!("\treset\t\t- reset the target");
+                REPLConnected::Dump { loc, words } => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        dump_memory(&mut probe, loc, words)
+                            .map_err(|e| println!("{}", e))
+                            .ok();
                     }
-                    REPLConnected::Info => {
-                        if let Some(mut probe) = probe.as_mut() {
-                            show_info(&mut probe).ok();
+                }
+                REPLConnected::Config(action) => handle_config_action(&mut config, action),
+                REPLConnected::Disconnect => {
+                    probe = None;
+                }
+                REPLConnected::Reset => {
+                    probe.as_mut().map(|mut p| reset(&mut p).ok());
+                }
+                REPLConnected::Rtt {
+                    start,
+                    len,
+                    elf_path,
+                } => {
+                    let defmt_table = match elf_path {
+                        Some(path) => match std::fs::read(&path)
+                            .map_err(|e| e.to_string())
+                            .and_then(|bytes| elf::load_defmt_table(&bytes).map_err(String::from))
+                        {
+                            Ok(table) => Some(table),
+                            Err(e) => {
+                                println!("Failed to load defmt table from '{}': {}", path, e);
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                    if let Some(mut probe) = probe.as_mut() {
+                        rtt_monitor(&mut probe, start, len, defmt_table.as_ref())
+                            .map_err(|e| println!("{}", e))
+                            .ok();
+                    }
+                }
+                REPLConnected::Scan => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        match scan_chain(&mut probe) {
+                            Ok(idcodes) => {
+                                println!("Found {} TAP(s) on the scan chain:", idcodes.len());
+                                for (n, (revision, partno, designer, reserved)) in
+                                    idcodes.iter().enumerate()
+                                {
+                                    println!(
+                                        "[{}]: Revision = {}, Part Number = {}, Designer = {:x}, Reserved = {}",
+                                        n, revision, partno, designer, reserved
+                                    );
+                                }
+                            }
+                            Err(e) => println!("{}", e),
                         }
                     }
-                    REPLConnected::Dump { loc, words } => {
-                        if let Some(mut probe) = probe.as_mut() {
-                            dump_memory(&mut probe, loc, words)
-                                .map_err(|e| println!("{}", e))
-                                .ok();
+                }
+                REPLConnected::Trace { channel, clock } => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        trace_target(&mut probe, channel, clock)
+                            .map_err(|e| println!("{}", e))
+                            .ok();
+                    }
+                }
+                REPLConnected::Write { addr, values } => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        write_memory(&mut probe, addr, &values)
+                            .map_err(|e| println!("{}", e))
+                            .ok();
+                    }
+                }
+                REPLConnected::Flash { path } => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        match std::fs::read(&path)
+                            .map_err(|e| e.to_string())
+                            .and_then(|bytes| elf::load_segments(&bytes).map_err(String::from))
+                        {
+                            Ok(segments) => {
+                                flash_segments(&mut probe, &segments)
+                                    .map_err(|e| println!("{}", e))
+                                    .ok();
+                            }
+                            Err(e) => println!("Failed to load '{}': {}", path, e),
                         }
                     }
-                    REPLConnected::Disconnect => {
-                        probe = None;
+                }
+                REPLConnected::Bin { addr, path } => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        match std::fs::read(&path) {
+                            Ok(data) => {
+                                let segments = vec![Segment { addr, data }];
+                                flash_segments(&mut probe, &segments)
+                                    .map_err(|e| println!("{}", e))
+                                    .ok();
+                            }
+                            Err(e) => println!("Failed to load '{}': {}", path, e),
+                        }
                     }
-                    REPLConnected::Reset => {
-                        probe.as_mut().map(|mut p| reset(&mut p).ok());
+                }
+                REPLConnected::Wreg { bank, addr, value } => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        write_dap_register(&mut probe, bank, addr, value)
+                            .map_err(|e| println!("{}", e))
+                            .ok();
                     }
-                    REPLConnected::Exit => break,
-                    REPLConnected::Continue => (),
                 }
-            }
+                REPLConnected::Halt => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        halt(&mut probe).map_err(|e| println!("{}", e)).ok();
+                    }
+                }
+                REPLConnected::Step => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        step(&mut probe).map_err(|e| println!("{}", e)).ok();
+                    }
+                }
+                REPLConnected::Go => {
+                    if let Some(mut probe) = probe.as_mut() {
+                        go(&mut probe).map_err(|e| println!("{}", e)).ok();
+                    }
+                }
+                REPLConnected::Exit => break,
+                REPLConnected::Continue => (),
+            },
         }
     }
 